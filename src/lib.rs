@@ -10,15 +10,31 @@ use jonases_tracing_util::log_simple_err_callback;
 use jonases_tracing_util::tracing::{event, Level};
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Lower bound, in seconds, for the remaining token lifetime that the
+/// refresh delay (90% of that lifetime) is computed from, so a token
+/// that is already expired (or about to be) does not spin the refresh
+/// loop.
+const MIN_REFRESH_DELAY_SECS: i64 = 30;
 
 pub mod error {
-  use actix_web::client::{JsonPayloadError, SendRequestError};
+  use actix_web::client::{PayloadError, SendRequestError};
+
+  use super::OAuthError;
 
   #[derive(Debug)]
   pub enum Error {
     SendRequestError(SendRequestError),
-    JsonPayloadError(JsonPayloadError),
+    PayloadError(PayloadError),
+    ParseError(serde_json::Error),
+    /// A valid HTTP response rejecting the request, with its raw body
+    /// preserved for diagnostics.
+    StatusCode(u16, String),
+    /// A standard OAuth2 token-error payload parsed out of the body,
+    /// letting callers tell `invalid_grant` apart from a transient
+    /// 5xx.
+    OAuthError(OAuthError),
   }
 
   impl From<SendRequestError> for Error {
@@ -27,9 +43,60 @@ pub mod error {
     }
   }
 
-  impl From<JsonPayloadError> for Error {
-    fn from(e: JsonPayloadError) -> Self {
-      Self::JsonPayloadError(e)
+  impl From<PayloadError> for Error {
+    fn from(e: PayloadError) -> Self {
+      Self::PayloadError(e)
+    }
+  }
+
+  impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+      Self::ParseError(e)
+    }
+  }
+}
+
+/// Circuit breaker guarding the token endpoint.
+///
+/// Once `failure_threshold` consecutive failures pile up the circuit
+/// is considered "open" and [`should_try`](Breaker::should_try) stops
+/// giving the go-ahead until `cooldown` has elapsed since the last
+/// attempt, at which point a single half-open probe is allowed
+/// through.
+struct Breaker {
+  failure_count: usize,
+  last_attempt: Instant,
+  failure_threshold: usize,
+  cooldown: Duration,
+}
+
+impl Breaker {
+  fn should_try(&self) -> bool {
+    self.failure_count < self.failure_threshold
+      || self.last_attempt.elapsed() > self.cooldown
+  }
+
+  fn is_open(&self) -> bool {
+    self.failure_count >= self.failure_threshold
+  }
+
+  fn fail(&mut self) {
+    self.failure_count += 1;
+    self.last_attempt = Instant::now();
+  }
+
+  fn succeed(&mut self) {
+    self.failure_count = 0;
+  }
+}
+
+impl Default for Breaker {
+  fn default() -> Self {
+    Breaker {
+      failure_count: 0,
+      last_attempt: Instant::now(),
+      failure_threshold: 5,
+      cooldown: Duration::from_secs(60),
     }
   }
 }
@@ -41,7 +108,16 @@ pub struct AccessToken {
 
 impl AccessToken {
   pub fn new(endpoint: String, token_request: TokenRequest) -> Self {
-    let inner = InnerAccessToken::new(endpoint, token_request);
+    Self::with_client_auth(endpoint, token_request, ClientAuth::FormBody)
+  }
+
+  pub fn with_client_auth(
+    endpoint: String,
+    token_request: TokenRequest,
+    client_auth: ClientAuth,
+  ) -> Self {
+    let inner =
+      InnerAccessToken::new(endpoint, token_request, client_auth);
 
     let access_token = AccessToken {
       inner: Arc::new(RwLock::new(inner)),
@@ -59,16 +135,19 @@ impl AccessToken {
       self.refresh_token(&client).await;
 
       loop {
-        actix_web::rt::time::delay_for({
-          let expires_in = match self.inner.read().await.expires_in()
-          {
-            Some(expires_in) => expires_in as f64,
-            None => 60.,
-          };
-
-          Duration::from_secs_f64(expires_in * 0.9_f64)
-        })
-        .await;
+        let delay = {
+          let inner = self.inner.read().await;
+          if inner.revoked {
+            break;
+          }
+          inner.refresh_delay()
+        };
+
+        actix_web::rt::time::delay_for(delay).await;
+
+        if self.inner.read().await.revoked {
+          break;
+        }
 
         self.refresh_token(&client).await;
       }
@@ -91,6 +170,43 @@ impl AccessToken {
     self.inner.read().await.token_response()
   }
 
+  pub async fn claims(&self) -> Option<Claims> {
+    self.inner.read().await.claims()
+  }
+
+  pub async fn expires_at(&self) -> Option<i64> {
+    self.inner.read().await.expires_at()
+  }
+
+  /// Revoke both the access and refresh tokens (RFC 7009) and drop the
+  /// cached response, which halts the background refresh loop.
+  pub async fn revoke(
+    &self,
+    revocation_endpoint: &str,
+  ) -> Result<(), Error> {
+    let client = Client::builder().disable_timeout().finish();
+    self
+      .inner
+      .write()
+      .await
+      .revoke(&client, revocation_endpoint)
+      .await
+  }
+
+  /// Introspect the current access token (RFC 7662).
+  pub async fn introspect(
+    &self,
+    introspection_endpoint: &str,
+  ) -> Result<Introspection, Error> {
+    let client = Client::builder().disable_timeout().finish();
+    self
+      .inner
+      .read()
+      .await
+      .introspect(&client, introspection_endpoint)
+      .await
+  }
+
   fn log_token_request(
     &self,
     token_request_result: Result<(), error::Error>,
@@ -107,17 +223,151 @@ struct InnerAccessToken {
   token_response: Option<TokenResponse>,
   endpoint: String,
   token_request: TokenRequest,
+  client_auth: ClientAuth,
+  breaker: Breaker,
+  revoked: bool,
 }
 
 impl InnerAccessToken {
   fn new(
     endpoint: String,
     token_request: TokenRequest,
+    client_auth: ClientAuth,
   ) -> InnerAccessToken {
     InnerAccessToken {
       token_response: None,
       endpoint,
       token_request,
+      client_auth,
+      breaker: Breaker::default(),
+      revoked: false,
+    }
+  }
+
+  async fn revoke(
+    &mut self,
+    client: &Client,
+    endpoint: &str,
+  ) -> Result<(), Error> {
+    if let Some(token_response) = self.token_response.clone() {
+      self
+        .post_form_with_client_auth(
+          client,
+          endpoint,
+          vec![
+            ("token", token_response.access_token),
+            ("token_type_hint", "access_token".to_owned()),
+          ],
+        )
+        .await?;
+
+      if let Some(refresh_token) = token_response.refresh_token {
+        self
+          .post_form_with_client_auth(
+            client,
+            endpoint,
+            vec![
+              ("token", refresh_token),
+              ("token_type_hint", "refresh_token".to_owned()),
+            ],
+          )
+          .await?;
+      }
+    }
+
+    self.token_response = None;
+    self.revoked = true;
+
+    Ok(())
+  }
+
+  async fn introspect(
+    &self,
+    client: &Client,
+    endpoint: &str,
+  ) -> Result<Introspection, Error> {
+    let access_token = match self.access_token() {
+      Some(access_token) => access_token,
+      None => return Ok(Introspection::default()),
+    };
+
+    let body = self
+      .post_form_with_client_auth(
+        client,
+        endpoint,
+        vec![
+          ("token", access_token),
+          ("token_type_hint", "access_token".to_owned()),
+        ],
+      )
+      .await?;
+
+    Ok(serde_json::from_slice(&body)?)
+  }
+
+  /// Post a form to `endpoint`, attaching client credentials the same
+  /// way the token request does (in-body or as an `Authorization:
+  /// Basic` header), and return the raw body on success.
+  async fn post_form_with_client_auth(
+    &self,
+    client: &Client,
+    endpoint: &str,
+    mut form: Vec<(&'static str, String)>,
+  ) -> Result<Vec<u8>, Error> {
+    let request = match self.client_auth {
+      ClientAuth::Basic => {
+        match self.token_request.basic_auth_header(&ClientAuth::Basic) {
+          Some(header) => {
+            client.post(endpoint).header("Authorization", header)
+          }
+          None => {
+            if let Some(client_id) = self.token_request.client_id() {
+              form.push(("client_id", client_id));
+            }
+            client.post(endpoint)
+          }
+        }
+      }
+      ClientAuth::FormBody => {
+        if let Some(client_id) = self.token_request.client_id() {
+          form.push(("client_id", client_id));
+        }
+        if let Some((_, client_secret)) =
+          self.token_request.client_credentials_pair()
+        {
+          form.push(("client_secret", client_secret));
+        }
+        client.post(endpoint)
+      }
+    };
+
+    let mut response = request
+      .send_form(&form)
+      .await
+      .map_err(log_simple_err_callback("error during connection"))?;
+
+    let body = response
+      .body()
+      .await
+      .map_err(log_simple_err_callback("error retrieving payload"))?;
+
+    if response.status().is_success() {
+      Ok(body.to_vec())
+    } else {
+      event!(
+        Level::ERROR,
+        body = %String::from_utf8_lossy(&*body),
+        status = %response.status(),
+      );
+
+      let status = response.status().as_u16();
+      Err(match OAuthError::from_body(&body) {
+        Some(oauth) => Error::OAuthError(oauth),
+        None => Error::StatusCode(
+          status,
+          String::from_utf8_lossy(&*body).into_owned(),
+        ),
+      })
     }
   }
 
@@ -125,16 +375,78 @@ impl InnerAccessToken {
     &mut self,
     client: &Client,
   ) -> Result<(), error::Error> {
-    self.token_response = Some(
-      client
-        .post(&self.endpoint)
-        .send_form(&self.token_request)
-        .await?
-        .json()
-        .await?,
-    );
+    if !self.breaker.should_try() {
+      return Ok(());
+    }
 
-    Ok(())
+    match self.request_token(client).await {
+      Ok(token_response) => {
+        self.token_response = Some(token_response);
+        self.breaker.succeed();
+        Ok(())
+      }
+      Err(e) => {
+        self.breaker.fail();
+        Err(e)
+      }
+    }
+  }
+
+  async fn request_token(
+    &self,
+    client: &Client,
+  ) -> Result<TokenResponse, error::Error> {
+    let mut response = self
+      .token_request
+      .send_form_with_auth(&self.endpoint, client, &self.client_auth)
+      .await?;
+
+    let body = response.body().await?;
+
+    if response.status().is_success() {
+      Ok(serde_json::from_slice(&*body)?)
+    } else {
+      let status = response.status().as_u16();
+      Err(match OAuthError::from_body(&body) {
+        Some(oauth) => error::Error::OAuthError(oauth),
+        None => error::Error::StatusCode(
+          status,
+          String::from_utf8_lossy(&*body).into_owned(),
+        ),
+      })
+    }
+  }
+
+  /// Delay until the next refresh attempt.
+  ///
+  /// While the circuit breaker is open we back off for the whole
+  /// `cooldown` instead of hammering the endpoint every refresh cycle.
+  fn refresh_delay(&self) -> Duration {
+    if self.breaker.is_open() {
+      return self.breaker.cooldown;
+    }
+
+    // Prefer the token's own `exp` claim over the server-reported
+    // `expires_in`, which drifts from the real validity because of
+    // clock skew and transport latency. Opaque (non-JWT) tokens have
+    // no claims and fall back to `expires_in`.
+    let lifetime = match self.expires_at() {
+      Some(exp) => (exp - now()).max(MIN_REFRESH_DELAY_SECS) as f64,
+      None => match self.expires_in() {
+        Some(expires_in) => expires_in as f64,
+        None => 60.,
+      },
+    };
+
+    Duration::from_secs_f64(lifetime * 0.9_f64)
+  }
+
+  fn claims(&self) -> Option<Claims> {
+    Claims::from_jwt(&self.access_token()?)
+  }
+
+  fn expires_at(&self) -> Option<i64> {
+    Some(self.claims()?.exp)
   }
 
   fn expires_in(&self) -> Option<i64> {
@@ -163,18 +475,51 @@ pub enum TokenRequest {
   ClientCredentials {
     client_id: String,
     client_secret: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
   },
   Password {
     username: String,
     password: String,
     client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
   },
   RefreshToken {
     refresh_token: String,
     client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
   },
 }
 
+/// Request body used for the client-credentials grant under
+/// `client_secret_basic`, where the credentials are carried in the
+/// `Authorization` header instead.
+#[derive(Serialize)]
+struct ClientCredentialsBody {
+  grant_type: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  scope: Option<String>,
+}
+
+/// How client credentials are presented to the token endpoint.
+#[derive(Clone, Debug)]
+pub enum ClientAuth {
+  /// `client_id`/`client_secret` travel in the request body
+  /// (`client_secret_post`).
+  FormBody,
+  /// `client_id`/`client_secret` travel in an `Authorization: Basic`
+  /// header (`client_secret_basic`).
+  Basic,
+}
+
+impl Default for ClientAuth {
+  fn default() -> Self {
+    ClientAuth::FormBody
+  }
+}
+
 impl TokenRequest {
   pub fn client_credentials(
     client_id: String,
@@ -183,6 +528,7 @@ impl TokenRequest {
     Self::ClientCredentials {
       client_id: client_id,
       client_secret: client_secret,
+      scope: None,
     }
   }
 
@@ -191,6 +537,7 @@ impl TokenRequest {
       username: username,
       password: password,
       client_id: None,
+      scope: None,
     }
   }
 
@@ -203,6 +550,7 @@ impl TokenRequest {
       username,
       password,
       client_id: Some(client_id),
+      scope: None,
     }
   }
 
@@ -210,6 +558,7 @@ impl TokenRequest {
     Self::RefreshToken {
       refresh_token,
       client_id: None,
+      scope: None,
     }
   }
 
@@ -220,6 +569,7 @@ impl TokenRequest {
     Self::RefreshToken {
       refresh_token,
       client_id: Some(client_id),
+      scope: None,
     }
   }
 
@@ -229,36 +579,154 @@ impl TokenRequest {
         username,
         password,
         client_id: _,
-      } => {
-        Self::password_with_client_id(username, password, client_id)
-      }
+        scope,
+      } => Self::Password {
+        username,
+        password,
+        client_id: Some(client_id),
+        scope,
+      },
       Self::RefreshToken {
         refresh_token,
         client_id: _,
-      } => {
-        Self::refresh_token_with_client_id(refresh_token, client_id)
-      }
+        scope,
+      } => Self::RefreshToken {
+        refresh_token,
+        client_id: Some(client_id),
+        scope,
+      },
       other => other,
     }
   }
 
+  /// Attach an OAuth2 `scope` to the request.
+  pub fn with_scope(self, scope: String) -> Self {
+    let scope = Some(scope);
+    match self {
+      Self::ClientCredentials {
+        client_id,
+        client_secret,
+        scope: _,
+      } => Self::ClientCredentials {
+        client_id,
+        client_secret,
+        scope,
+      },
+      Self::Password {
+        username,
+        password,
+        client_id,
+        scope: _,
+      } => Self::Password {
+        username,
+        password,
+        client_id,
+        scope,
+      },
+      Self::RefreshToken {
+        refresh_token,
+        client_id,
+        scope: _,
+      } => Self::RefreshToken {
+        refresh_token,
+        client_id,
+        scope,
+      },
+    }
+  }
+
   pub async fn send(
     &self,
     url: &str,
   ) -> Result<TokenResponse, Error> {
     let client = Client::builder().disable_timeout().finish();
-    self.send_with_client(url, &client).await
+    self.send_with_client(url, &client, &ClientAuth::FormBody).await
+  }
+
+  /// `Authorization: Basic` header value for `client_secret_basic`
+  /// endpoints, or `None` when credentials should stay in the body.
+  fn basic_auth_header(
+    &self,
+    client_auth: &ClientAuth,
+  ) -> Option<String> {
+    match (client_auth, self) {
+      (
+        ClientAuth::Basic,
+        Self::ClientCredentials {
+          client_id,
+          client_secret,
+          ..
+        },
+      ) => Some(format!(
+        "Basic {}",
+        base64::encode(format!("{}:{}", client_id, client_secret))
+      )),
+      _ => None,
+    }
+  }
+
+  /// The `client_id` carried by this grant, if any. Used so
+  /// revocation/introspection identify the client the same way the
+  /// token request does.
+  fn client_id(&self) -> Option<String> {
+    match self {
+      Self::ClientCredentials { client_id, .. } => {
+        Some(client_id.clone())
+      }
+      Self::Password { client_id, .. } => client_id.clone(),
+      Self::RefreshToken { client_id, .. } => client_id.clone(),
+    }
+  }
+
+  /// The `(client_id, client_secret)` pair for the client-credentials
+  /// grant, used to authenticate revocation/introspection requests.
+  fn client_credentials_pair(&self) -> Option<(String, String)> {
+    match self {
+      Self::ClientCredentials {
+        client_id,
+        client_secret,
+        ..
+      } => Some((client_id.clone(), client_secret.clone())),
+      _ => None,
+    }
+  }
+
+  /// Post this request, honouring the selected client-authentication
+  /// mode. With `client_secret_basic` the credentials live in the
+  /// `Authorization` header, so they are dropped from the body and
+  /// only the grant type (and scope, when requested) is sent.
+  fn send_form_with_auth(
+    &self,
+    url: &str,
+    client: &Client,
+    client_auth: &ClientAuth,
+  ) -> actix_web::client::SendClientRequest {
+    let request = match self.basic_auth_header(client_auth) {
+      Some(header) => client.post(url).header("Authorization", header),
+      None => client.post(url),
+    };
+
+    match (client_auth, self) {
+      (ClientAuth::Basic, Self::ClientCredentials { scope, .. }) => {
+        request.send_form(&ClientCredentialsBody {
+          grant_type: "client_credentials",
+          scope: scope.clone(),
+        })
+      }
+      _ => request.send_form(self),
+    }
   }
 
   pub async fn send_with_client(
     &self,
     url: &str,
     client: &Client,
+    client_auth: &ClientAuth,
   ) -> Result<TokenResponse, Error> {
-    let mut response =
-      client.post(url).send_form(&self).await.map_err(
-        log_simple_err_callback("error during connection"),
-      )?;
+    let mut response = self
+      .send_form_with_auth(url, client, client_auth)
+      .await
+      .map_err(log_simple_err_callback("error during connection"))?;
 
     let body = response
       .body()
@@ -278,11 +746,89 @@ impl TokenRequest {
         status = %response.status(),
       );
 
-      Err(Error::StatusCode(response.status().as_u16()))
+      let status = response.status().as_u16();
+      Err(match OAuthError::from_body(&body) {
+        Some(oauth) => Error::OAuthError(oauth),
+        None => Error::StatusCode(
+          status,
+          String::from_utf8_lossy(&*body).into_owned(),
+        ),
+      })
     }
   }
 }
 
+/// Claims carried in the payload segment of a JWT `access_token`.
+///
+/// Only the fields that are useful for scheduling refreshes and for
+/// letting callers inspect the token are modelled explicitly; every
+/// other claim is kept verbatim under [`Claims::extra`].
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct Claims {
+  pub exp: i64,
+  #[serde(default)]
+  pub iat: Option<i64>,
+  #[serde(default)]
+  pub nbf: Option<i64>,
+  #[serde(default)]
+  pub scope: Option<String>,
+  #[serde(default)]
+  pub sub: Option<String>,
+  #[serde(flatten)]
+  pub extra: std::collections::HashMap<String, serde_json::Value>,
+}
+
+impl Claims {
+  /// Decode the payload of a JWT without verifying its signature.
+  ///
+  /// Returns `None` for opaque tokens that are not shaped like a JWT,
+  /// so callers can transparently fall back to `expires_in`.
+  fn from_jwt(token: &str) -> Option<Claims> {
+    let payload = token.split('.').nth(1)?;
+    let decoded =
+      base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&decoded).ok()
+  }
+}
+
+/// The standard OAuth2 token-error response as defined by RFC 6749
+/// section 5.2.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct OAuthError {
+  pub error: String,
+  pub error_description: Option<String>,
+  pub error_uri: Option<String>,
+}
+
+impl OAuthError {
+  fn from_body(body: &[u8]) -> Option<OAuthError> {
+    serde_json::from_slice(body).ok()
+  }
+}
+
+/// The token-introspection response defined by RFC 7662.
+///
+/// Only the commonly consulted members are modelled explicitly; any
+/// additional members the authorization server returns are kept under
+/// [`Introspection::extra`].
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Introspection {
+  pub active: bool,
+  pub scope: Option<String>,
+  pub exp: Option<i64>,
+  pub sub: Option<String>,
+  pub client_id: Option<String>,
+  #[serde(flatten)]
+  pub extra: std::collections::HashMap<String, serde_json::Value>,
+}
+
+fn now() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs() as i64)
+    .unwrap_or(0)
+}
+
 #[derive(Deserialize, Clone, Debug, PartialEq)]
 pub struct TokenResponse {
   pub access_token: String,
@@ -302,7 +848,8 @@ pub enum Error {
   ParseError,
   SendRequestError,
   PayloadError,
-  StatusCode(u16),
+  StatusCode(u16, String),
+  OAuthError(OAuthError),
 }
 
 impl From<serde_json::Error> for Error {
@@ -323,12 +870,213 @@ impl From<actix_web::client::SendRequestError> for Error {
   }
 }
 
+/// actix-web integration: an extractor that yields a ready [`Bearer`]
+/// and a client wrapper that keeps outgoing requests authorized with a
+/// long-lived [`AccessToken`].
+///
+/// The module provides no actix-service `Transform` middleware: a
+/// server-side `Transform` only sees *inbound* `ServiceRequest`s, so
+/// attaching the bearer to *outgoing* client requests and the single
+/// retry on a downstream `401` are done by the [`BearerClient`] wrapper
+/// at the client layer.
+pub mod middleware {
+  use std::time::Duration;
+
+  use actix_web::client::{Client, ClientRequest};
+  use actix_web::error::ErrorServiceUnavailable;
+  use actix_web::dev::Payload;
+  use actix_web::{Error, FromRequest, HttpRequest};
+
+  use actix_web_httpauth::headers::authorization::Bearer;
+
+  use futures::future::LocalBoxFuture;
+
+  use super::AccessToken;
+
+  /// How long [`BearerToken`] waits for the first token fetch before
+  /// giving up, and how often it re-checks in the meantime.
+  const FIRST_TOKEN_TIMEOUT: Duration = Duration::from_secs(5);
+  const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+  /// Extractor that hands a handler the current [`Bearer`] taken from
+  /// the [`AccessToken`] stored in the application data.
+  ///
+  /// It waits for the very first token fetch to complete instead of
+  /// requiring callers to sleep before reading the token, but only up
+  /// to [`FIRST_TOKEN_TIMEOUT`] so a down authorization server cannot
+  /// pile requests up indefinitely.
+  pub struct BearerToken(pub Bearer);
+
+  impl std::ops::Deref for BearerToken {
+    type Target = Bearer;
+
+    fn deref(&self) -> &Self::Target {
+      &self.0
+    }
+  }
+
+  impl FromRequest for BearerToken {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+      let access_token = req
+        .app_data::<actix_web::web::Data<AccessToken>>()
+        .map(|data| data.get_ref().clone());
+
+      Box::pin(async move {
+        let access_token = access_token.ok_or_else(|| {
+          ErrorServiceUnavailable("AccessToken missing from app data")
+        })?;
+
+        let mut remaining =
+          (FIRST_TOKEN_TIMEOUT.as_millis() / POLL_INTERVAL.as_millis())
+            as u32;
+
+        loop {
+          if let Some(bearer) = access_token.bearer().await {
+            return Ok(BearerToken(bearer));
+          }
+
+          if remaining == 0 {
+            return Err(ErrorServiceUnavailable(
+              "access token not yet available",
+            ));
+          }
+          remaining -= 1;
+
+          actix_web::rt::time::delay_for(POLL_INTERVAL).await;
+        }
+      })
+    }
+  }
+
+  /// Set the `Authorization: Bearer` header on an outgoing request.
+  pub fn authorize(
+    request: ClientRequest,
+    bearer: &Bearer,
+  ) -> ClientRequest {
+    request.bearer_auth(bearer.token())
+  }
+
+  /// Wraps an [`actix_web::client::Client`] so every request it issues
+  /// carries the current bearer and, on a `401`, refreshes the token
+  /// and replays the request once.
+  #[derive(Clone)]
+  pub struct BearerClient {
+    client: Client,
+    access_token: AccessToken,
+  }
+
+  impl BearerClient {
+    pub fn new(client: Client, access_token: AccessToken) -> Self {
+      BearerClient {
+        client,
+        access_token,
+      }
+    }
+
+    /// The wrapped client, for building requests.
+    pub fn client(&self) -> &Client {
+      &self.client
+    }
+
+    /// Attach the current bearer to `request`, if one is available.
+    pub async fn authorize(
+      &self,
+      request: ClientRequest,
+    ) -> ClientRequest {
+      match self.access_token.bearer().await {
+        Some(bearer) => authorize(request, &bearer),
+        None => request,
+      }
+    }
+
+    /// Send a request built by `builder`, authorized with the current
+    /// bearer. On a downstream `401` the token is refreshed and the
+    /// request is rebuilt and sent once more, so token rotation stays
+    /// invisible to application code. The response status and body are
+    /// returned.
+    pub async fn send<F>(
+      &self,
+      builder: F,
+    ) -> Result<(u16, Vec<u8>), super::Error>
+    where
+      F: Fn(&Client) -> ClientRequest,
+    {
+      let mut response = self
+        .authorize(builder(&self.client))
+        .await
+        .send()
+        .await?;
+
+      if response.status().as_u16() != 401 {
+        let status = response.status().as_u16();
+        return Ok((status, response.body().await?.to_vec()));
+      }
+
+      self.access_token.refresh_token(&self.client).await;
+
+      let mut response = self
+        .authorize(builder(&self.client))
+        .await
+        .send()
+        .await?;
+
+      let status = response.status().as_u16();
+      Ok((status, response.body().await?.to_vec()))
+    }
+  }
+}
+
 #[cfg(test)]
 mod tests {
-  use super::TokenRequest;
+  use super::{Claims, OAuthError, TokenRequest};
 
   use serde_urlencoded::to_string;
 
+  #[test]
+  fn decoding_jwt_claims() {
+    let payload = base64::encode_config(
+      br#"{"exp":1516239022,"scope":"openid profile"}"#,
+      base64::URL_SAFE_NO_PAD,
+    );
+    let token = format!("header.{}.signature", payload);
+
+    let claims = Claims::from_jwt(&token).unwrap();
+
+    assert_eq!(claims.exp, 1516239022);
+    assert_eq!(claims.scope.as_deref(), Some("openid profile"));
+  }
+
+  #[test]
+  fn decoding_opaque_token_yields_no_claims() {
+    assert!(Claims::from_jwt("an-opaque-token").is_none());
+  }
+
+  #[test]
+  fn parsing_oauth_error_payload() {
+    let body = br#"{
+      "error": "invalid_grant",
+      "error_description": "Token is not active"
+    }"#;
+
+    let error = OAuthError::from_body(body).unwrap();
+
+    assert_eq!(error.error, "invalid_grant");
+    assert_eq!(
+      error.error_description.as_deref(),
+      Some("Token is not active"),
+    );
+    assert_eq!(error.error_uri, None);
+  }
+
+  #[test]
+  fn parsing_non_oauth_error_body_yields_none() {
+    assert!(OAuthError::from_body(b"upstream timeout").is_none());
+  }
+
   #[test]
   fn serializing_client_credentials_token_request_to_url_encoded() {
     let token_request = TokenRequest::client_credentials(
@@ -389,6 +1137,25 @@ mod tests {
     );
   }
 
+  #[test]
+  fn serializing_client_credentials_token_request_with_scope_to_url_encoded(
+  ) {
+    let token_request = TokenRequest::client_credentials(
+      String::from("some id"),
+      String::from("some secret"),
+    )
+    .with_scope(String::from("some scope"));
+
+    assert_eq!(
+      to_string(token_request).unwrap(),
+      concat!(
+        "grant_type=client_credentials",
+        "&client_id=some+id&client_secret=some+secret",
+        "&scope=some+scope",
+      )
+    );
+  }
+
   #[test]
   fn serializing_refresh_token_request_with_id_to_url_encoded() {
     let token_request = TokenRequest::refresh_token_with_client_id(
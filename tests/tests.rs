@@ -1,5 +1,9 @@
-use std::time::Duration;
+use actix_web::dev::Payload;
+use actix_web::test::TestRequest;
+use actix_web::web::Data;
+use actix_web::FromRequest;
 
+use actix_oidc_token::middleware::BearerToken;
 use actix_oidc_token::{AccessToken, TokenRequest};
 
 #[actix_rt::test]
@@ -34,9 +38,13 @@ async fn access_token() {
     tr
   );
 
-  // delay so that task that gets the token response has time to
-  // finish
-  actix_web::rt::time::delay_for(Duration::from_secs(1)).await;
+  // the `BearerToken` extractor waits for the first token fetch, so no
+  // manual delay is needed
+  let req = TestRequest::default()
+    .app_data(Data::new(at))
+    .to_http_request();
 
-  at.bearer().await.unwrap();
+  BearerToken::from_request(&req, &mut Payload::None)
+    .await
+    .unwrap();
 }